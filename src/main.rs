@@ -1,6 +1,9 @@
 mod sdf;
 
-use crate::sdf::{Cube, Scene, Sdf, Sphere};
+use crate::sdf::{
+    Capsule, Cube, Material, Plane, RoundedBox, Scene, Sdf, SmoothUnion, Sphere, Torus,
+};
+use rand::Rng;
 use raylib::prelude::*;
 use rayon::prelude::*;
 use std::time::{Duration, Instant};
@@ -8,10 +11,39 @@ use std::time::{Duration, Instant};
 const RENDER_VIEWPORT: i32 = 300;
 const ACTUAL_VIEWPORT: i32 = 1500;
 
+/// Small offset used to lift shadow- and reflection-ray origins off surfaces.
+const EPSILON: f32 = 0.0001;
+/// Penumbra sharpness for the soft-shadow march (larger = sharper).
+const SHADOW_SOFTNESS: f32 = 16.0;
+/// Number of jittered rays averaged per pixel for anti-aliasing and depth-of-field.
+const SAMPLES_PER_PIXEL: u32 = 4;
+/// Maximum number of reflection bounces traced per primary ray.
+const MAX_REFLECTION_DEPTH: u32 = 4;
+
 pub struct Camera {
     eye: Vector3,
     target: Vector3,
     up: Vector3,
+    /// Horizontal look angle, in radians.
+    yaw: f32,
+    /// Vertical look angle, in radians (clamped to avoid gimbal flip).
+    pitch: f32,
+    /// Lens aperture diameter. `0` gives a pinhole camera (everything in focus).
+    aperture: f32,
+    /// Distance along the view ray at which geometry is perfectly sharp.
+    focus_distance: f32,
+}
+
+impl Camera {
+    /// The forward look direction derived from the current yaw/pitch angles.
+    fn forward(&self) -> Vector3 {
+        Vector3::new(
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            self.pitch.cos() * self.yaw.cos(),
+        )
+        .normalized()
+    }
 }
 
 pub struct LightSource {
@@ -26,20 +58,29 @@ pub struct LightSource {
 pub struct Lighting {
     /// Ambient intensity
     ia: f32,
-    /// Shininess reflection constant
-    alpha: f32,
     /// Light sources
     light_sources: Vec<LightSource>,
 }
 
 impl Lighting {
-    fn illuminate(&self, camera: &Camera, p: Vector3, object: &Box<dyn Sdf>) -> Vector3 {
+    fn illuminate(
+        &self,
+        eye: Vector3,
+        p: Vector3,
+        object: &dyn Sdf,
+        scene: &Scene,
+        depth: u32,
+    ) -> Vector3 {
         let mut ip = Vector3::default();
 
         let n = object.surface_normal(p);
+        let material = object.material();
 
         // View direction
-        let v = (camera.eye - p).normalized();
+        let v = (eye - p).normalized();
+
+        // Screen-space ambient occlusion darkens crevices.
+        let ao = self.ambient_occlusion(scene, p, n);
 
         for ls in &self.light_sources {
             // Light direction
@@ -48,14 +89,61 @@ impl Lighting {
             let r = (n * (l.dot(n)) * 2.0 - l).normalized();
 
             let diffuse_f = l.dot(n).max(0.0);
-            let specular_f = v.dot(r).max(0.0).powf(self.alpha);
+            let specular_f = v.dot(r).max(0.0).powf(material.shininess);
+
+            // Cast a soft shadow ray from the surface toward the light.
+            let light_dist = (ls.pos - p).length();
+            let shadow = scene.soft_shadow(p + n * EPSILON * 8.0, l, light_dist, SHADOW_SOFTNESS);
 
-            ip += ls.diffuse * (diffuse_f + self.ia) + ls.specular * specular_f
+            ip += (ls.diffuse * material.albedo * diffuse_f
+                + ls.specular * material.specular * specular_f)
+                * shadow
+                + ls.diffuse * material.albedo * (self.ia * ao)
         }
 
         ip *= 255.;
-        ip.clamp(0. ..255.)
+        let mut color = ip.clamp(0. ..255.);
+
+        // Recursive mirror reflection.
+        if depth > 0 && material.reflectivity > 0.0 {
+            let d = (p - eye).normalized();
+            let reflected_dir = d - n * (2.0 * d.dot(n));
+            let origin = p + n * EPSILON * 8.0;
+
+            if let Some((hit, object_id)) = scene.ray_march(origin, reflected_dir) {
+                let hit_object = scene.get_object(object_id);
+                let reflected = self.illuminate(origin, hit, hit_object, scene, depth - 1);
+                color = color * (1.0 - material.reflectivity) + reflected * material.reflectivity;
+            }
+        }
+
+        color
     }
+
+    /// Sample the scene SDF at five increasing steps along the surface normal to
+    /// approximate how occluded `p` is, returning a factor in `[0, 1]`.
+    fn ambient_occlusion(&self, scene: &Scene, p: Vector3, n: Vector3) -> f32 {
+        const DECAY: f32 = 0.95;
+
+        let mut ao = 0.0;
+        let mut step = 0.0;
+
+        for i in 0..5 {
+            step += 0.05;
+            let d = scene.nearest_distance(p + n * step);
+            ao += (step - d) * DECAY.powi(i);
+        }
+
+        (1.0 - ao).clamp(0.0, 1.0)
+    }
+}
+
+/// Sample a uniformly distributed point on a disk of the given `radius`, returned
+/// as `(u, v)` offsets in the lens plane.
+fn sample_disk<R: Rng + ?Sized>(rng: &mut R, radius: f32) -> (f32, f32) {
+    let r = radius * rng.gen::<f32>().sqrt();
+    let theta = std::f32::consts::TAU * rng.gen::<f32>();
+    (r * theta.cos(), r * theta.sin())
 }
 
 fn v3_into_color(v: Vector3) -> Color {
@@ -68,34 +156,121 @@ fn v3_into_color(v: Vector3) -> Color {
 }
 
 fn main() {
-    let (mut rl, thread) = init()
-        .size(ACTUAL_VIEWPORT, ACTUAL_VIEWPORT)
-        .title("Space")
-        .build();
-
-    rl.set_target_fps(30);
-
     let mut camera = Camera {
         eye: Vector3::new(0., 0., -2.),
         target: Vector3::new(0., 0., 0.),
         up: Vector3::new(0., -1., 0.),
+        yaw: 0.,
+        pitch: 0.,
+        aperture: 0.,
+        focus_distance: 2.,
     };
 
     let sphere = Box::new(Sphere {
         id: 0,
         center: Vector3::new(0., 0., 0.),
         radius: 1.,
+        material: Material {
+            albedo: Vector3::new(0.2, 0.4, 0.9),
+            specular: Vector3::new(1., 1., 1.),
+            shininess: 32.0,
+            reflectivity: 0.4,
+        },
     });
 
     let cube = Box::new(Cube {
         id: 1,
         center: Vector3::new(-2., 0., 0.),
         length: 1.,
+        material: Material {
+            albedo: Vector3::new(0.9, 0.3, 0.2),
+            specular: Vector3::new(1., 1., 1.),
+            shininess: 16.0,
+            reflectivity: 0.0,
+        },
+    });
+
+    let floor = Box::new(Plane {
+        id: 2,
+        normal: Vector3::new(0., 1., 0.),
+        height: 1.5,
+        material: Material {
+            albedo: Vector3::new(0.6, 0.6, 0.6),
+            specular: Vector3::new(0.2, 0.2, 0.2),
+            shininess: 8.0,
+            reflectivity: 0.0,
+        },
+    });
+
+    let torus = Box::new(Torus {
+        id: 3,
+        center: Vector3::new(2.5, 0., 0.),
+        major: 0.8,
+        minor: 0.25,
+        material: Material {
+            albedo: Vector3::new(0.2, 0.8, 0.4),
+            specular: Vector3::new(1., 1., 1.),
+            shininess: 24.0,
+            reflectivity: 0.1,
+        },
+    });
+
+    let rounded_box = Box::new(RoundedBox {
+        id: 4,
+        center: Vector3::new(0., 0., 3.),
+        length: 0.6,
+        radius: 0.2,
+        material: Material {
+            albedo: Vector3::new(0.8, 0.7, 0.2),
+            specular: Vector3::new(1., 1., 1.),
+            shininess: 16.0,
+            reflectivity: 0.0,
+        },
+    });
+
+    let capsule = Box::new(Capsule {
+        id: 5,
+        a: Vector3::new(3., -1., 2.),
+        b: Vector3::new(3., 1., 2.),
+        radius: 0.3,
+        material: Material {
+            albedo: Vector3::new(0.7, 0.2, 0.8),
+            specular: Vector3::new(1., 1., 1.),
+            shininess: 32.0,
+            reflectivity: 0.0,
+        },
+    });
+
+    // A CSG blob: a sphere and a cube smoothly fused into one organic shape.
+    let blob = Box::new(SmoothUnion {
+        id: 6,
+        a: Box::new(Sphere {
+            id: 100,
+            center: Vector3::new(0., 2.5, 0.),
+            radius: 0.7,
+            material: Material {
+                albedo: Vector3::new(0.9, 0.5, 0.7),
+                specular: Vector3::new(1., 1., 1.),
+                shininess: 32.0,
+                reflectivity: 0.2,
+            },
+        }),
+        b: Box::new(Cube {
+            id: 101,
+            center: Vector3::new(0.6, 2.5, 0.),
+            length: 0.5,
+            material: Material {
+                albedo: Vector3::new(0.9, 0.5, 0.7),
+                specular: Vector3::new(1., 1., 1.),
+                shininess: 32.0,
+                reflectivity: 0.2,
+            },
+        }),
+        k: 0.5,
     });
 
     let lighting = Lighting {
         ia: 0.1,
-        alpha: 32.0,
         light_sources: vec![LightSource {
             pos: Vector3::new(5., 5., 5.),
             specular: Vector3::new(1., 1., 1.),
@@ -103,15 +278,51 @@ fn main() {
         }],
     };
 
-    let scene = Scene::new(vec![cube, sphere]);
+    let scene = Scene::new(vec![cube, sphere, floor, torus, rounded_box, capsule, blob]);
+
+    // Headless path: render a single full-resolution still and exit without
+    // ever opening the interactive window.
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(pos) = args.iter().position(|a| a == "--headless") {
+        let output = args.get(pos + 1).map(String::as_str).unwrap_or("render.ppm");
+        let rgb = render_headless(90., &camera, &lighting, &scene, MAX_REFLECTION_DEPTH);
+        write_render(output, ACTUAL_VIEWPORT, ACTUAL_VIEWPORT, &rgb)
+            .expect("failed to write render");
+        println!("Wrote {}", output);
+        return;
+    }
+
+    let (mut rl, thread) = init()
+        .size(ACTUAL_VIEWPORT, ACTUAL_VIEWPORT)
+        .title("Space")
+        .build();
+
+    rl.set_target_fps(30);
+
+    // Progressive-refinement accumulator: sample sums per coarse pixel, plus the
+    // number of samples folded in so far. Reset whenever the camera moves.
+    let mut accum = vec![Vector3::default(); (RENDER_VIEWPORT * RENDER_VIEWPORT) as usize];
+    let mut total_samples = 0_u32;
 
     while !rl.window_should_close() {
-        check_movement(&mut camera, &rl);
+        if check_movement(&mut camera, &rl) {
+            accum.iter_mut().for_each(|c| *c = Vector3::default());
+            total_samples = 0;
+        }
 
         let mut draw_handle = rl.begin_drawing(&thread);
         draw_handle.clear_background(Color::BLACK);
 
-        let frame_time = draw(&mut draw_handle, 90., &camera, &lighting, &scene);
+        let frame_time = draw(
+            &mut draw_handle,
+            90.,
+            &camera,
+            &lighting,
+            &scene,
+            MAX_REFLECTION_DEPTH,
+            &mut accum,
+            &mut total_samples,
+        );
 
         draw_handle.draw_text(
             &format!("Frame time: {0:#?}", frame_time),
@@ -123,70 +334,147 @@ fn main() {
     }
 }
 
+/// A configured renderer: the scene and lighting plus the per-frame camera basis
+/// and projection parameters shared by every ray.
+struct View<'a> {
+    camera: &'a Camera,
+    lighting: &'a Lighting,
+    scene: &'a Scene,
+    max_depth: u32,
+    forward: Vector3,
+    right: Vector3,
+    true_up: Vector3,
+    ratio: f32,
+    scale: f32,
+    width: i32,
+}
+
+impl<'a> View<'a> {
+    fn new(
+        camera: &'a Camera,
+        lighting: &'a Lighting,
+        scene: &'a Scene,
+        max_depth: u32,
+        fov: f32,
+        width: i32,
+    ) -> Self {
+        let forward = (camera.target - camera.eye).normalized();
+        let right = forward.cross(camera.up).normalized();
+        let true_up = right.cross(forward);
+
+        Self {
+            camera,
+            lighting,
+            scene,
+            max_depth,
+            forward,
+            right,
+            true_up,
+            ratio: 1.0,
+            scale: ((fov * 0.5).to_radians()).tan(),
+            width,
+        }
+    }
+
+    /// Shade a single (optionally jittered) sample through pixel `(x, y)`,
+    /// returning the background color when the ray escapes the scene.
+    fn sample(&self, x: i32, y: i32, jitter_x: f32, jitter_y: f32, rng: &mut impl Rng) -> Vector3 {
+        let camera = self.camera;
+
+        let x_normalized = (x as f32 + 0.5 + jitter_x) / (self.width as f32) * 2.0 - 1.0;
+        let y_normalized = (y as f32 + 0.5 + jitter_y) / (self.width as f32) * 2.0 - 1.0;
+
+        let pixel_camera_space =
+            Vector3::new(x_normalized * self.ratio * self.scale, y_normalized * self.scale, 1.);
+
+        let direction = ((self.right * pixel_camera_space.x)
+            + (self.true_up * pixel_camera_space.y)
+            + (self.forward * pixel_camera_space.z))
+            .normalized();
+
+        // Thin-lens depth of field: jitter the origin over the aperture disk
+        // and re-aim at the focal point so in-focus geometry stays sharp.
+        let (origin, ray) = if camera.aperture > 0.0 {
+            let (du, dv) = sample_disk(rng, camera.aperture * 0.5);
+            let origin = camera.eye + self.right * du + self.true_up * dv;
+            let focal_point = camera.eye + direction * camera.focus_distance;
+            (origin, (focal_point - origin).normalized())
+        } else {
+            (camera.eye, direction)
+        };
+
+        if let Some((point, object_id)) = self.scene.ray_march(origin, ray) {
+            let object = self.scene.get_object(object_id);
+            self.lighting
+                .illuminate(origin, point, object, self.scene, self.max_depth)
+        } else {
+            Vector3::default()
+        }
+    }
+}
+
+/// All pixel coordinates of a square `side`×`side` grid, in `par_iter` order.
+fn pixel_grid(side: i32) -> Vec<(i32, i32)> {
+    let mut pixels = Vec::with_capacity((side * side) as usize);
+    for x in 0..side {
+        for y in 0..side {
+            pixels.push((x, y));
+        }
+    }
+    pixels
+}
+
+/// Accumulate one more batch of jittered samples into `accum` (indexed in
+/// [`pixel_grid`] order) and blit the running average to the screen. Called once
+/// per frame so the coarse image progressively sharpens while the camera is still.
 fn draw(
     dh: &mut RaylibDrawHandle,
     fov: f32,
     camera: &Camera,
     lighting: &Lighting,
     scene: &Scene,
+    max_depth: u32,
+    accum: &mut [Vector3],
+    total_samples: &mut u32,
 ) -> Duration {
-    let ratio = (RENDER_VIEWPORT as f32) / (RENDER_VIEWPORT as f32);
-    let scale = ((fov * 0.5).to_radians()).tan();
-
-    let forward = (camera.target - camera.eye).normalized();
-    let right = forward.cross(camera.up).normalized();
-    let true_up = right.cross(forward);
+    let view = View::new(camera, lighting, scene, max_depth, fov, RENDER_VIEWPORT);
 
     let start = Instant::now();
 
-    let x_pixels = 0..RENDER_VIEWPORT;
-    let y_pixels = 0..RENDER_VIEWPORT;
-
-    let mut pixels = Vec::with_capacity(x_pixels.len() * y_pixels.len());
+    let pixels = pixel_grid(RENDER_VIEWPORT);
 
-    for x in x_pixels {
-        for y in y_pixels.clone() {
-            pixels.push((x, y));
-        }
-    }
-
-    let to_draw = pixels
+    let batch = pixels
         .par_iter()
         .map(|(x, y)| {
-            let x = *x;
-            let y = *y;
-            let x_normalized = (x as f32 + 0.5) / (RENDER_VIEWPORT as f32) * 2.0 - 1.0;
-            let y_normalized = (y as f32 + 0.5) / (RENDER_VIEWPORT as f32) * 2.0 - 1.0;
-
-            let pixel_camera_space =
-                Vector3::new(x_normalized * ratio * scale, y_normalized * scale, 1.); // forward
-
-            let ray = ((right * pixel_camera_space.x)
-                + (true_up * pixel_camera_space.y)
-                + (forward * pixel_camera_space.z))
-                .normalized();
-
-            if let Some((point, object_id)) = scene.ray_march(camera.eye, ray) {
-                let object = scene.get_object(object_id);
-                let color = lighting.illuminate(camera, point, object);
-
-                Some((x, y, color))
-            } else {
-                None
+            let mut rng = rand::thread_rng();
+            let mut accumulated = Vector3::default();
+
+            for _ in 0..SAMPLES_PER_PIXEL {
+                // Sub-pixel jitter for anti-aliasing.
+                let jitter_x = rng.gen::<f32>() - 0.5;
+                let jitter_y = rng.gen::<f32>() - 0.5;
+                accumulated += view.sample(*x, *y, jitter_x, jitter_y, &mut rng);
             }
+
+            accumulated
         })
-        .flatten()
         .collect::<Vec<_>>();
 
+    for (slot, sample) in accum.iter_mut().zip(batch) {
+        *slot += sample;
+    }
+    *total_samples += SAMPLES_PER_PIXEL;
+
+    let normalization = 1.0 / (*total_samples as f32);
     let ratio = ACTUAL_VIEWPORT / RENDER_VIEWPORT;
 
-    for (x, y, color) in to_draw {
+    for ((x, y), color) in pixels.into_iter().zip(accum.iter()) {
         dh.draw_rectangle(
             x * ratio,
             y * ratio,
             ratio.max(1),
             ratio.max(1),
-            v3_into_color(color),
+            v3_into_color((*color * normalization).clamp(0. ..255.)),
         );
     }
 
@@ -195,43 +483,131 @@ fn draw(
     end - start
 }
 
-fn check_movement(camera: &mut Camera, rl: &RaylibHandle) {
+/// Render the scene at full [`ACTUAL_VIEWPORT`] resolution with a single ray per
+/// pixel and return the image as a row-major RGB byte buffer.
+fn render_headless(
+    fov: f32,
+    camera: &Camera,
+    lighting: &Lighting,
+    scene: &Scene,
+    max_depth: u32,
+) -> Vec<u8> {
+    let view = View::new(camera, lighting, scene, max_depth, fov, ACTUAL_VIEWPORT);
+
+    // Row-major (y outer, x inner) so the buffer matches the PPM/PNG layout.
+    let mut pixels = Vec::with_capacity((ACTUAL_VIEWPORT * ACTUAL_VIEWPORT) as usize);
+    for y in 0..ACTUAL_VIEWPORT {
+        for x in 0..ACTUAL_VIEWPORT {
+            pixels.push((x, y));
+        }
+    }
+
+    pixels
+        .par_iter()
+        .flat_map(|(x, y)| {
+            let mut rng = rand::thread_rng();
+            let color = view.sample(*x, *y, 0.0, 0.0, &mut rng).clamp(0. ..255.);
+            [color.x as u8, color.y as u8, color.z as u8]
+        })
+        .collect()
+}
+
+/// Write a row-major RGB buffer to `path` as a binary (P6) PPM file.
+fn write_ppm(path: &str, width: i32, height: i32, rgb: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut file = std::fs::File::create(path)?;
+    write!(file, "P6\n{} {}\n255\n", width, height)?;
+    file.write_all(rgb)?;
+    Ok(())
+}
+
+/// Persist a rendered RGB buffer to `path`, choosing PPM for a `.ppm` extension
+/// and PNG otherwise.
+fn write_render(path: &str, width: i32, height: i32, rgb: &[u8]) -> std::io::Result<()> {
+    if path.ends_with(".ppm") {
+        write_ppm(path, width, height, rgb)
+    } else {
+        image::save_buffer_with_format(
+            path,
+            rgb,
+            width as u32,
+            height as u32,
+            image::ColorType::Rgb8,
+            image::ImageFormat::Png,
+        )
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+    }
+}
+
+/// Apply camera input for this frame, returning `true` if any movement or look
+/// input was applied (so the caller can reset the progressive accumulator).
+fn check_movement(camera: &mut Camera, rl: &RaylibHandle) -> bool {
+    let mut moved = false;
+
     macro_rules! key {
         ($key:expr) => {{
             let kbd: KeyboardKey = $key;
-            rl.is_key_pressed(kbd) || rl.is_key_down(kbd)
+            let pressed = rl.is_key_pressed(kbd) || rl.is_key_down(kbd);
+            moved |= pressed;
+            pressed
         }};
     }
 
-    // Move
-    // Forward-Back
+    const MOVE_SPEED: f32 = 0.1;
+    const LOOK_SPEED: f32 = 0.03;
+    // Keep just shy of straight up/down to avoid gimbal flip.
+    const PITCH_LIMIT: f32 = 1.5533;
+
+    // Pan (look around): Q/E yaw, arrow keys pitch, with optional mouse look.
+    if key!(KeyboardKey::KEY_Q) {
+        camera.yaw -= LOOK_SPEED;
+    }
+    if key!(KeyboardKey::KEY_E) {
+        camera.yaw += LOOK_SPEED;
+    }
+    if key!(KeyboardKey::KEY_UP) {
+        camera.pitch += LOOK_SPEED;
+    }
+    if key!(KeyboardKey::KEY_DOWN) {
+        camera.pitch -= LOOK_SPEED;
+    }
+
+    let mouse_delta = rl.get_mouse_delta();
+    if mouse_delta.x != 0.0 || mouse_delta.y != 0.0 {
+        camera.yaw += mouse_delta.x * LOOK_SPEED * 0.1;
+        camera.pitch -= mouse_delta.y * LOOK_SPEED * 0.1;
+        moved = true;
+    }
+
+    camera.pitch = camera.pitch.clamp(-PITCH_LIMIT, PITCH_LIMIT);
+
+    // Movement is relative to where the camera is currently looking.
+    let forward = camera.forward();
+    let right = forward.cross(camera.up).normalized();
+
     if key!(KeyboardKey::KEY_W) {
-        camera.eye.z += 0.1;
-        camera.target.z += 0.1;
+        camera.eye += forward * MOVE_SPEED;
     }
     if key!(KeyboardKey::KEY_S) {
-        camera.eye.z -= 0.1;
-        camera.target.z -= 0.1;
+        camera.eye -= forward * MOVE_SPEED;
     }
-
-    // Left-right
     if key!(KeyboardKey::KEY_A) {
-        camera.eye.x -= 0.1;
-        camera.target.x -= 0.1;
+        camera.eye -= right * MOVE_SPEED;
     }
     if key!(KeyboardKey::KEY_D) {
-        camera.eye.x += 0.1;
-        camera.target.x += 0.1;
+        camera.eye += right * MOVE_SPEED;
     }
 
     if key!(KeyboardKey::KEY_SPACE) {
-        camera.eye.y += 0.1;
+        camera.eye.y += MOVE_SPEED;
     }
     if key!(KeyboardKey::KEY_C) {
-        camera.eye.y -= 0.1;
+        camera.eye.y -= MOVE_SPEED;
     }
 
-    // Pan
-    if key!(KeyboardKey::KEY_Q) {}
-    if key!(KeyboardKey::KEY_E) {}
+    // Re-aim the target from the (possibly) updated eye and look direction.
+    camera.target = camera.eye + camera.forward();
+
+    moved
 }
@@ -7,11 +7,26 @@ const EPSILON: f32 = 0.0001;
 
 pub type SdfId = usize;
 
+/// Surface appearance carried by each [`Sdf`] object.
+pub struct Material {
+    /// Diffuse albedo, modulating the light's diffuse contribution per channel.
+    pub albedo: Vector3,
+    /// Specular color of the highlight.
+    pub specular: Vector3,
+    /// Phong shininess exponent for this surface.
+    pub shininess: f32,
+    /// Mirror reflectivity in `[0, 1]`; `0` disables reflections.
+    pub reflectivity: f32,
+}
+
 pub trait Sdf: Sync {
     fn id(&self) -> SdfId;
 
     fn sdf(&self, v: Vector3) -> f32;
 
+    /// The material describing how this object's surface is shaded.
+    fn material(&self) -> &Material;
+
     fn dist(&self, v: Vector3) -> (f32, SdfId) {
         (self.sdf(v), self.id())
     }
@@ -39,6 +54,7 @@ pub struct Sphere {
     pub id: SdfId,
     pub center: Vector3,
     pub radius: f32,
+    pub material: Material,
 }
 
 impl Sdf for Sphere {
@@ -46,6 +62,10 @@ impl Sdf for Sphere {
         self.id
     }
 
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
     fn sdf(&self, v: Vector3) -> f32 {
         (v - self.center).length() - self.radius
     }
@@ -55,17 +75,38 @@ pub struct Cube {
     pub id: SdfId,
     pub center: Vector3,
     pub length: f32,
+    pub material: Material,
 }
 
 fn absolute(vector3: Vector3) -> Vector3 {
     Vector3::new(vector3.x.abs(), vector3.y.abs(), vector3.z.abs())
 }
 
+/// Linear interpolation between `a` and `b` by `t`.
+fn mix(a: f32, b: f32, t: f32) -> f32 {
+    a * (1.0 - t) + b * t
+}
+
+/// Polynomial smooth minimum. Larger `k` widens the blend region.
+fn smin(a: f32, b: f32, k: f32) -> f32 {
+    let h = (0.5 + 0.5 * (b - a) / k).clamp(0.0, 1.0);
+    mix(b, a, h) - k * h * (1.0 - h)
+}
+
+/// Smooth maximum, defined as the negated smooth minimum of the negated inputs.
+fn smax(a: f32, b: f32, k: f32) -> f32 {
+    -smin(-a, -b, k)
+}
+
 impl Sdf for Cube {
     fn id(&self) -> SdfId {
         self.id
     }
 
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
     fn sdf(&self, p: Vector3) -> f32 {
         // https://iquilezles.org/articles/distfunctions/
         // float sdBox( vec3 p, vec3 b )
@@ -80,6 +121,288 @@ impl Sdf for Cube {
     }
 }
 
+/// Hard boolean union of two SDFs (`min(a, b)`).
+pub struct Union {
+    pub id: SdfId,
+    pub a: Box<dyn Sdf>,
+    pub b: Box<dyn Sdf>,
+}
+
+impl Sdf for Union {
+    fn id(&self) -> SdfId {
+        self.id
+    }
+
+    fn material(&self) -> &Material {
+        self.a.material()
+    }
+
+    fn sdf(&self, v: Vector3) -> f32 {
+        self.a.sdf(v).min(self.b.sdf(v))
+    }
+}
+
+/// Hard boolean intersection of two SDFs (`max(a, b)`).
+pub struct Intersection {
+    pub id: SdfId,
+    pub a: Box<dyn Sdf>,
+    pub b: Box<dyn Sdf>,
+}
+
+impl Sdf for Intersection {
+    fn id(&self) -> SdfId {
+        self.id
+    }
+
+    fn material(&self) -> &Material {
+        self.a.material()
+    }
+
+    fn sdf(&self, v: Vector3) -> f32 {
+        self.a.sdf(v).max(self.b.sdf(v))
+    }
+}
+
+/// Hard boolean subtraction of `b` from `a` (`max(a, -b)`).
+pub struct Subtraction {
+    pub id: SdfId,
+    pub a: Box<dyn Sdf>,
+    pub b: Box<dyn Sdf>,
+}
+
+impl Sdf for Subtraction {
+    fn id(&self) -> SdfId {
+        self.id
+    }
+
+    fn material(&self) -> &Material {
+        self.a.material()
+    }
+
+    fn sdf(&self, v: Vector3) -> f32 {
+        self.a.sdf(v).max(-self.b.sdf(v))
+    }
+}
+
+/// Smooth union of two SDFs blended with [`smin`].
+pub struct SmoothUnion {
+    pub id: SdfId,
+    pub a: Box<dyn Sdf>,
+    pub b: Box<dyn Sdf>,
+    pub k: f32,
+}
+
+impl Sdf for SmoothUnion {
+    fn id(&self) -> SdfId {
+        self.id
+    }
+
+    fn material(&self) -> &Material {
+        self.a.material()
+    }
+
+    fn sdf(&self, v: Vector3) -> f32 {
+        smin(self.a.sdf(v), self.b.sdf(v), self.k)
+    }
+}
+
+/// Smooth intersection of two SDFs blended with [`smax`].
+pub struct SmoothIntersection {
+    pub id: SdfId,
+    pub a: Box<dyn Sdf>,
+    pub b: Box<dyn Sdf>,
+    pub k: f32,
+}
+
+impl Sdf for SmoothIntersection {
+    fn id(&self) -> SdfId {
+        self.id
+    }
+
+    fn material(&self) -> &Material {
+        self.a.material()
+    }
+
+    fn sdf(&self, v: Vector3) -> f32 {
+        smax(self.a.sdf(v), self.b.sdf(v), self.k)
+    }
+}
+
+/// Smooth subtraction of `b` from `a` blended with [`smax`].
+pub struct SmoothSubtraction {
+    pub id: SdfId,
+    pub a: Box<dyn Sdf>,
+    pub b: Box<dyn Sdf>,
+    pub k: f32,
+}
+
+impl Sdf for SmoothSubtraction {
+    fn id(&self) -> SdfId {
+        self.id
+    }
+
+    fn material(&self) -> &Material {
+        self.a.material()
+    }
+
+    fn sdf(&self, v: Vector3) -> f32 {
+        smax(self.a.sdf(v), -self.b.sdf(v), self.k)
+    }
+}
+
+/// Infinitely repeat a child SDF on a lattice with per-axis cell size `cell`.
+pub struct Repetition {
+    pub id: SdfId,
+    pub child: Box<dyn Sdf>,
+    pub cell: Vector3,
+}
+
+impl Sdf for Repetition {
+    fn id(&self) -> SdfId {
+        self.id
+    }
+
+    fn material(&self) -> &Material {
+        self.child.material()
+    }
+
+    fn sdf(&self, p: Vector3) -> f32 {
+        let c = self.cell;
+        let q = Vector3::new(
+            p.x - c.x * (p.x / c.x).round(),
+            p.y - c.y * (p.y / c.y).round(),
+            p.z - c.z * (p.z / c.z).round(),
+        );
+        self.child.sdf(q)
+    }
+}
+
+/// Twist a child SDF around the Y axis, rotating `p.xz` by `k * p.y`.
+pub struct Twist {
+    pub id: SdfId,
+    pub child: Box<dyn Sdf>,
+    pub k: f32,
+}
+
+impl Sdf for Twist {
+    fn id(&self) -> SdfId {
+        self.id
+    }
+
+    fn material(&self) -> &Material {
+        self.child.material()
+    }
+
+    fn sdf(&self, p: Vector3) -> f32 {
+        let angle = self.k * p.y;
+        let (s, c) = angle.sin_cos();
+        let q = Vector3::new(c * p.x - s * p.z, p.y, s * p.x + c * p.z);
+        self.child.sdf(q)
+    }
+}
+
+pub struct Torus {
+    pub id: SdfId,
+    pub center: Vector3,
+    /// Distance from the center of the tube to the center of the torus.
+    pub major: f32,
+    /// Radius of the tube.
+    pub minor: f32,
+    pub material: Material,
+}
+
+impl Sdf for Torus {
+    fn id(&self) -> SdfId {
+        self.id
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn sdf(&self, p: Vector3) -> f32 {
+        let p = p - self.center;
+        let radial = (p.x * p.x + p.z * p.z).sqrt() - self.major;
+        (radial * radial + p.y * p.y).sqrt() - self.minor
+    }
+}
+
+pub struct Plane {
+    pub id: SdfId,
+    /// Unit normal of the plane.
+    pub normal: Vector3,
+    /// Offset of the plane: it contains the points where `dot(p, normal) == -height`.
+    pub height: f32,
+    pub material: Material,
+}
+
+impl Sdf for Plane {
+    fn id(&self) -> SdfId {
+        self.id
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn sdf(&self, p: Vector3) -> f32 {
+        p.dot(self.normal) + self.height
+    }
+}
+
+pub struct RoundedBox {
+    pub id: SdfId,
+    pub center: Vector3,
+    pub length: f32,
+    /// Corner rounding radius.
+    pub radius: f32,
+    pub material: Material,
+}
+
+impl Sdf for RoundedBox {
+    fn id(&self) -> SdfId {
+        self.id
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn sdf(&self, p: Vector3) -> f32 {
+        let q = absolute(p - self.center) - self.length;
+        let zero = Vector3::default();
+
+        q.max(zero).length() + q.y.max(q.z).max(q.x).min(0.0) - self.radius
+    }
+}
+
+pub struct Capsule {
+    pub id: SdfId,
+    /// Start of the capsule's core segment.
+    pub a: Vector3,
+    /// End of the capsule's core segment.
+    pub b: Vector3,
+    pub radius: f32,
+    pub material: Material,
+}
+
+impl Sdf for Capsule {
+    fn id(&self) -> SdfId {
+        self.id
+    }
+
+    fn material(&self) -> &Material {
+        &self.material
+    }
+
+    fn sdf(&self, p: Vector3) -> f32 {
+        let pa = p - self.a;
+        let ba = self.b - self.a;
+        let h = (pa.dot(ba) / ba.dot(ba)).clamp(0.0, 1.0);
+        (pa - ba * h).length() - self.radius
+    }
+}
+
 pub struct Scene {
     objects: HashMap<SdfId, Box<dyn Sdf>>,
 }
@@ -99,6 +422,39 @@ impl Scene {
         self.objects.get(&id).unwrap().as_ref()
     }
 
+    /// Distance to the closest object in the scene (the union SDF value).
+    pub fn nearest_distance(&self, point: Vector3) -> f32 {
+        self.objects
+            .values()
+            .map(|obj| obj.sdf(point))
+            .fold(f32::INFINITY, f32::min)
+    }
+
+    /// March a shadow ray from `origin` along `dir` up to `max_dist`, returning a
+    /// visibility factor in `[0, 1]`. `0` means fully occluded, `1` fully lit; larger
+    /// `k` sharpens the penumbra.
+    pub fn soft_shadow(&self, origin: Vector3, dir: Vector3, max_dist: f32, k: f32) -> f32 {
+        let mut res = 1.0_f32;
+        let mut t = EPSILON * 10.0;
+
+        for _ in 0..MAX_MARCHING_STEPS {
+            if t >= max_dist {
+                break;
+            }
+
+            let h = self.nearest_distance(origin + dir * t);
+
+            if h < EPSILON {
+                return 0.0;
+            }
+
+            res = res.min(k * h / t);
+            t += h;
+        }
+
+        res
+    }
+
     /// Ray-march until something is reached. Returns the point where the ray has it
     /// as well as the id of the object hit.
     pub fn ray_march(&self, pos: Vector3, ray: Vector3) -> Option<(Vector3, SdfId)> {
@@ -128,3 +484,175 @@ impl Scene {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOL: f32 = 1e-4;
+
+    fn mat() -> Material {
+        Material {
+            albedo: Vector3::new(1., 1., 1.),
+            specular: Vector3::new(1., 1., 1.),
+            shininess: 32.0,
+            reflectivity: 0.0,
+        }
+    }
+
+    fn close(a: f32, b: f32) {
+        assert!((a - b).abs() < TOL, "expected {b}, got {a}");
+    }
+
+    #[test]
+    fn smin_smax_blend() {
+        // Equal inputs dip below/above by k/4.
+        close(smin(1.0, 1.0, 1.0), 0.75);
+        close(smax(1.0, 1.0, 1.0), 1.25);
+        // With a vanishing blend width it degrades to the hard min/max.
+        close(smin(1.0, 2.0, 1e-4), 1.0);
+        close(smax(1.0, 2.0, 1e-4), 2.0);
+    }
+
+    #[test]
+    fn torus_distance() {
+        let torus = Torus {
+            id: 0,
+            center: Vector3::default(),
+            major: 1.0,
+            minor: 0.25,
+            material: mat(),
+        };
+
+        close(torus.sdf(Vector3::new(1.0, 0.0, 0.0)), -0.25); // tube center
+        close(torus.sdf(Vector3::new(1.25, 0.0, 0.0)), 0.0); // on surface
+        close(torus.sdf(Vector3::new(2.0, 0.0, 0.0)), 0.75); // outside
+    }
+
+    #[test]
+    fn capsule_distance() {
+        let capsule = Capsule {
+            id: 0,
+            a: Vector3::new(-1.0, 0.0, 0.0),
+            b: Vector3::new(1.0, 0.0, 0.0),
+            radius: 0.5,
+            material: mat(),
+        };
+
+        close(capsule.sdf(Vector3::new(0.0, 0.0, 0.0)), -0.5); // on the axis
+        close(capsule.sdf(Vector3::new(0.0, 0.5, 0.0)), 0.0); // on surface
+        close(capsule.sdf(Vector3::new(2.0, 0.0, 0.0)), 0.5); // past the cap
+    }
+
+    #[test]
+    fn plane_distance() {
+        let plane = Plane {
+            id: 0,
+            normal: Vector3::new(0.0, 1.0, 0.0),
+            height: 0.0,
+            material: mat(),
+        };
+
+        close(plane.sdf(Vector3::new(0.0, 2.0, 0.0)), 2.0);
+        close(plane.sdf(Vector3::new(0.0, -1.5, 0.0)), -1.5);
+    }
+
+    #[test]
+    fn rounded_box_distance() {
+        let rb = RoundedBox {
+            id: 0,
+            center: Vector3::default(),
+            length: 1.0,
+            radius: 0.2,
+            material: mat(),
+        };
+
+        close(rb.sdf(Vector3::new(1.2, 0.0, 0.0)), 0.0); // on rounded face
+        close(rb.sdf(Vector3::new(0.0, 0.0, 0.0)), -1.2); // deep inside
+    }
+
+    fn unit_sphere(id: SdfId, x: f32) -> Box<dyn Sdf> {
+        Box::new(Sphere {
+            id,
+            center: Vector3::new(x, 0.0, 0.0),
+            radius: 1.0,
+            material: mat(),
+        })
+    }
+
+    #[test]
+    fn boolean_operators() {
+        let p = Vector3::default();
+
+        // Sphere at origin (sdf -1 here) unioned/intersected with one at x=3 (sdf 2).
+        let union = Union {
+            id: 10,
+            a: unit_sphere(0, 0.0),
+            b: unit_sphere(1, 3.0),
+        };
+        close(union.sdf(p), -1.0);
+
+        let intersection = Intersection {
+            id: 11,
+            a: unit_sphere(0, 0.0),
+            b: unit_sphere(1, 3.0),
+        };
+        close(intersection.sdf(p), 2.0);
+
+        let subtraction = Subtraction {
+            id: 12,
+            a: unit_sphere(0, 0.0),
+            b: unit_sphere(1, 3.0),
+        };
+        close(subtraction.sdf(p), -1.0);
+    }
+
+    #[test]
+    fn smooth_operators_bounded_by_hard() {
+        let p = Vector3::default();
+
+        let smooth_union = SmoothUnion {
+            id: 20,
+            a: unit_sphere(0, 0.0),
+            b: unit_sphere(1, 3.0),
+            k: 0.5,
+        };
+        // A smooth union never exceeds the hard union.
+        assert!(smooth_union.sdf(p) <= -1.0 + TOL);
+
+        let smooth_intersection = SmoothIntersection {
+            id: 21,
+            a: unit_sphere(0, 0.0),
+            b: unit_sphere(1, 3.0),
+            k: 0.5,
+        };
+        assert!(smooth_intersection.sdf(p) >= 2.0 - TOL);
+
+        let smooth_subtraction = SmoothSubtraction {
+            id: 22,
+            a: unit_sphere(0, 0.0),
+            b: unit_sphere(1, 3.0),
+            k: 0.5,
+        };
+        assert!(smooth_subtraction.sdf(p) >= -1.0 - TOL);
+    }
+
+    #[test]
+    fn domain_operators() {
+        // Repetition on a size-4 lattice folds x=4 back onto the origin cell.
+        let repetition = Repetition {
+            id: 30,
+            child: unit_sphere(0, 0.0),
+            cell: Vector3::new(4.0, 4.0, 4.0),
+        };
+        close(repetition.sdf(Vector3::new(4.0, 0.0, 0.0)), -1.0);
+
+        // A zero-angle twist is the identity.
+        let twist = Twist {
+            id: 31,
+            child: unit_sphere(0, 0.0),
+            k: 0.0,
+        };
+        close(twist.sdf(Vector3::default()), -1.0);
+    }
+}